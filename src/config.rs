@@ -0,0 +1,135 @@
+use chrono::{Datelike, Duration, NaiveDate};
+use clap::ValueEnum;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use std::{fs::read_to_string, path::Path, path::PathBuf};
+
+fn default_tolerance() -> Decimal {
+    Decimal::new(1, 0)
+}
+
+/// GST rate table and ledger names driving the reverse-charge / LUT handling
+/// and the per-voucher tax reconciliation in the `gst` module.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GstConfig {
+    /// Valid ad-valorem rates as percentages, e.g. `[5, 12, 18, 28]`.
+    #[serde(default)]
+    pub rates: Vec<Decimal>,
+    #[serde(default)]
+    pub cgst_ledgers: Vec<String>,
+    #[serde(default)]
+    pub sgst_ledgers: Vec<String>,
+    #[serde(default)]
+    pub igst_ledgers: Vec<String>,
+    /// Output ledger the reverse-charge liability is posted to.
+    #[serde(default)]
+    pub rcm_payable_ledger: Option<String>,
+    /// Input ledger the reverse-charge credit is posted to.
+    #[serde(default)]
+    pub rcm_creditable_ledger: Option<String>,
+    /// Absolute rupee tolerance before a reconciliation mismatch is flagged.
+    #[serde(default = "default_tolerance")]
+    pub tolerance: Decimal,
+}
+
+/// Output backend selected on the command line with `--format`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Tally's `ENVELOPE` import XML (the default).
+    TallyXml,
+    /// Plain-text double-entry journal consumed by ledger-cli/hledger.
+    Ledger,
+    /// An `.ods` workbook with a sheet per voucher type and a trial balance.
+    Ods,
+}
+
+/// A single named window to export. Either supplied explicitly under
+/// `[[ranges]]` or synthesized month-by-month from a top-level `from`/`to`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DateRange {
+    pub name: String,
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+}
+
+/// Declarative description of an export run, loaded from a TOML file via
+/// `--config`. Replaces the date windows and collection list that used to be
+/// hardcoded in `export_data`, so a different period or collection set no
+/// longer means editing source.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub uri: String,
+    pub org: String,
+    pub account_map: PathBuf,
+    pub voucher_type_map: PathBuf,
+    /// Optional AuditPlus→Tally override table for account-type groups (see
+    /// `ledger_master::get_account_type`). When unset, only the built-in
+    /// defaults apply.
+    #[serde(default)]
+    pub account_type_map: Option<PathBuf>,
+    pub collections: Vec<String>,
+    pub output_dir: PathBuf,
+    #[serde(default)]
+    pub from: Option<NaiveDate>,
+    #[serde(default)]
+    pub to: Option<NaiveDate>,
+    #[serde(default)]
+    pub ranges: Vec<DateRange>,
+    #[serde(default)]
+    pub gst: Option<GstConfig>,
+    /// Only stream accounts updated after the start (00:00 UTC) of this date,
+    /// for a delta sync instead of a full chart-of-accounts pull.
+    #[serde(default)]
+    pub since: Option<NaiveDate>,
+    /// Page size for the account/ledger streaming queries. Unset means fetch
+    /// everything in one page.
+    #[serde(default)]
+    pub page_size: Option<i64>,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Self, crate::error::ExportError> {
+        let raw = read_to_string(path)?;
+        let config: Config = toml::from_str(&raw)?;
+        Ok(config)
+    }
+
+    /// The windows to iterate over: the explicit `[[ranges]]` if any were
+    /// given, otherwise the `from`/`to` pair split into calendar months.
+    pub fn date_ranges(&self) -> Vec<DateRange> {
+        if !self.ranges.is_empty() {
+            return self.ranges.clone();
+        }
+        match (self.from, self.to) {
+            (Some(from), Some(to)) => split_into_months(from, to),
+            _ => Vec::new(),
+        }
+    }
+}
+
+fn last_day_of_month(year: i32, month: u32) -> NaiveDate {
+    let (next_year, next_month) = if month == 12 {
+        (year + 1, 1)
+    } else {
+        (year, month + 1)
+    };
+    NaiveDate::from_ymd(next_year, next_month, 1) - Duration::days(1)
+}
+
+fn split_into_months(from: NaiveDate, to: NaiveDate) -> Vec<DateRange> {
+    let mut ranges = Vec::new();
+    let mut start = from;
+    while start <= to {
+        let end = last_day_of_month(start.year(), start.month()).min(to);
+        ranges.push(DateRange {
+            name: start.format("%Y-%m").to_string(),
+            from: start,
+            to: end,
+        });
+        start = end + Duration::days(1);
+    }
+    ranges
+}
@@ -0,0 +1,36 @@
+use thiserror::Error;
+
+/// Errors raised while exporting. Infrastructure failures (Mongo, IO, CSV,
+/// BSON) abort the run. The voucher-level variants (unknown account, unknown
+/// voucher type, empty party-ledger candidate list, unbalanced voucher) are
+/// collected per voucher so one bad record does not kill a multi-month export;
+/// an unknown account type met while building the ledger masters ends the run.
+#[derive(Debug, Error)]
+pub enum ExportError {
+    #[error("database error: {0}")]
+    Mongo(#[from] mongodb::error::Error),
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("csv error: {0}")]
+    Csv(#[from] csv::Error),
+    #[error("config error: {0}")]
+    Toml(#[from] toml::de::Error),
+    #[error("bson error: {0}")]
+    Bson(#[from] mongodb::bson::de::Error),
+    #[error("http error: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("xml error: {0}")]
+    Xml(String),
+    #[error("ods error: {0}")]
+    Ods(String),
+    #[error("unknown account id: {0}")]
+    UnknownAccount(String),
+    #[error("unknown voucher type: {0}")]
+    UnknownVoucherType(String),
+    #[error("unknown account type: {0}")]
+    UnknownAccountType(String),
+    #[error("no party-ledger candidate for voucher {0}")]
+    EmptyPartyLedger(String),
+    #[error("voucher {voucher} does not balance (sum {sum})")]
+    UnbalancedVoucher { voucher: String, sum: String },
+}
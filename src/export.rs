@@ -1,12 +1,36 @@
 use chrono::{Datelike, Duration, NaiveDate, NaiveTime, TimeZone, Utc};
 use futures::TryStreamExt;
 use mongodb::{
-    bson::{doc, from_document, oid::ObjectId, Document},
+    bson::{doc, from_document, oid::ObjectId, DateTime, Document},
     options::{AggregateOptions, FindOptions},
     Database,
 };
-use serde::{Deserialize, Serialize};
-use std::{cmp::Ordering, fs::File, io::Write};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize, Serializer};
+use std::{cmp::Ordering, fs::File, fs::read_to_string, io::Write};
+
+use crate::config::{Config, OutputFormat};
+use crate::error::ExportError;
+use crate::stock::{InventoryEntry, StockLedger, StockTransaction};
+
+/// Serialize a monetary value to the fixed 2-dp scale Tally expects in its
+/// `AMOUNT` element, rounding half-to-even so cents stay exact.
+pub(crate) fn serialize_amount<S>(amount: &Decimal, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.serialize_str(&format!("{:.2}", amount.round_dp(2)))
+}
+
+/// Serialize a `YYYY-MM-DD` date to the `yyyymmdd` Tally's `DATE` element
+/// expects. The field stays dashed on the `Voucher` value itself so the
+/// `ledger`/`ods` backends, which format it their own way, are unaffected.
+fn serialize_tally_date<S>(date: &str, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.serialize_str(&date.replace('-', ""))
+}
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "UPPERCASE")]
@@ -85,14 +109,15 @@ pub struct LedgerEntry {
     pub ledger_name: String,
     #[serde(rename = "ISDEEMEDPOSITIVE")]
     pub is_deemed_positive: String,
-    pub amount: f64,
+    #[serde(serialize_with = "serialize_amount")]
+    pub amount: Decimal,
 }
 
 impl LedgerEntry {
-    pub fn new(ledger_name: String, amount: f64) -> Self {
+    pub fn new(ledger_name: String, amount: Decimal) -> Self {
         Self {
             ledger_name,
-            is_deemed_positive: if amount < 0.0 {
+            is_deemed_positive: if amount < Decimal::ZERO {
                 "Yes".to_string()
             } else {
                 "No".to_string()
@@ -105,6 +130,13 @@ impl LedgerEntry {
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "UPPERCASE")]
 pub struct Voucher {
+    // Tally's import XML carries the voucher type and the create/alter/delete
+    // action as attributes on the `VOUCHER` tag itself, not as child elements.
+    #[serde(rename = "@VCHTYPE")]
+    pub vch_type: String,
+    #[serde(rename = "@ACTION")]
+    pub action: String,
+    #[serde(serialize_with = "serialize_tally_date")]
     pub date: String,
     #[serde(rename = "REFERENCE", skip_serializing_if = "Option::is_none")]
     pub ref_no: Option<String>,
@@ -116,8 +148,17 @@ pub struct Voucher {
     pub party_ledger: String,
     #[serde(rename = "VOUCHERNUMBER")]
     pub voucher_no: Option<String>,
+    #[serde(rename = "NARRATION", skip_serializing_if = "Option::is_none")]
+    pub narration: Option<String>,
     #[serde(rename = "ALLLEDGERENTRIES.LIST")]
     pub ledger_entries: Vec<LedgerEntry>,
+    #[serde(
+        rename = "INVENTORYENTRIES.LIST",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub inventory_entries: Vec<InventoryEntry>,
+    #[serde(rename = "ISREVERSECHARGE", skip_serializing_if = "Option::is_none")]
+    pub is_reverse_charge: Option<String>,
 }
 
 impl Voucher {
@@ -128,16 +169,23 @@ impl Voucher {
         voucher_type: String,
         party_ledger: String,
         voucher_no: Option<String>,
+        narration: Option<String>,
         ledger_entries: Vec<LedgerEntry>,
+        inventory_entries: Vec<InventoryEntry>,
     ) -> Self {
         Self {
+            vch_type: voucher_type.clone(),
+            action: "Create".to_string(),
             date,
             ref_no,
             ref_date,
             voucher_type,
             party_ledger,
             voucher_no,
+            narration,
             ledger_entries,
+            inventory_entries,
+            is_reverse_charge: None,
         }
     }
 }
@@ -152,7 +200,13 @@ pub struct NameMap {
 #[serde(rename_all = "camelCase")]
 pub struct Transaction {
     pub account: String,
-    pub amount: f64,
+    // `amount` comes off Mongo's `$subtract`/`$round` double arithmetic in
+    // `get_query` below, so it already carries whatever cent-level rounding
+    // that aggregation introduced — decoding it into a `Decimal` here keeps
+    // everything downstream exact, but does not recover precision already
+    // lost on the database side.
+    #[serde(with = "rust_decimal::serde::float")]
+    pub amount: Decimal,
     pub account_type: String,
 }
 
@@ -166,11 +220,13 @@ pub struct AGVoucher {
     pub voucher_type: String,
     pub voucher_no: Option<String>,
     pub trns: Vec<Transaction>,
+    #[serde(default)]
+    pub stock_trns: Vec<StockTransaction>,
     pub lut: Option<bool>,
     pub rcm: Option<bool>,
 }
 
-fn cmp_f64(a: &f64, b: &f64) -> Ordering {
+fn cmp_decimal(a: &Decimal, b: &Decimal) -> Ordering {
     if a < b {
         return Ordering::Less;
     } else if a > b {
@@ -179,7 +235,10 @@ fn cmp_f64(a: &f64, b: &f64) -> Ordering {
     return Ordering::Equal;
 }
 
-fn get_voucher_type(voucher_type: &str, voucher_type_map: &Vec<NameMap>) -> String {
+fn get_voucher_type(
+    voucher_type: &str,
+    voucher_type_map: &Vec<NameMap>,
+) -> Result<String, ExportError> {
     let vtype = match voucher_type {
         "SALE" => "Sales".to_string(),
         "CREDIT_NOTE" => "Credit Note".to_string(),
@@ -189,7 +248,7 @@ fn get_voucher_type(voucher_type: &str, voucher_type_map: &Vec<NameMap>) -> Stri
         "RECEIPT" => "Receipt".to_string(),
         "JOURNAL" => "Journal".to_string(),
         "CONTRA" => "Contra".to_string(),
-        _ => panic!("Invalid voucher type found"),
+        other => return Err(ExportError::UnknownVoucherType(other.to_string())),
     };
     let voucher_type_name =
         if let Some(name) = voucher_type_map.iter().find(|x| x.auditplus == vtype) {
@@ -197,17 +256,78 @@ fn get_voucher_type(voucher_type: &str, voucher_type_map: &Vec<NameMap>) -> Stri
         } else {
             vtype
         };
-    voucher_type_name
+    Ok(voucher_type_name)
+}
+
+/// Options for streaming the chart of accounts out incrementally: only
+/// records changed after `since`, in `_id`-ordered pages of `page_size`
+/// resumed from `cursor`.
+#[derive(Debug, Default, Clone)]
+pub struct ExportOptions {
+    pub since: Option<DateTime>,
+    pub page_size: Option<i64>,
+    pub cursor: Option<ObjectId>,
 }
 
-fn get_name_map(map_str: String) -> Vec<NameMap> {
+/// Fetch one page of accounts honoring `opts`, returning the documents and the
+/// `_id` cursor to resume from (the last document's id, if any).
+async fn get_accounts(
+    db: &Database,
+    opts: &ExportOptions,
+) -> Result<(Vec<Document>, Option<ObjectId>), ExportError> {
+    let mut filter = doc! {};
+    if let Some(since) = opts.since {
+        filter.insert("updatedAt", doc! {"$gt": since});
+    }
+    if let Some(cursor) = opts.cursor {
+        filter.insert("_id", doc! {"$gt": cursor});
+    }
+    let mut builder = FindOptions::builder()
+        .projection(doc! {"name":1,"id": {"$toString":"$_id"}})
+        .sort(doc! {"_id": 1});
+    if let Some(page_size) = opts.page_size {
+        builder = builder.limit(page_size);
+    }
+    let docs = db
+        .collection::<Document>("accounts")
+        .find(filter, builder.build())
+        .await?
+        .try_collect::<Vec<Document>>()
+        .await?;
+    let next = docs.last().and_then(|d| d.get_object_id("_id").ok());
+    Ok((docs, next))
+}
+
+/// Resolve a Mongo account id to its Tally ledger name, applying the
+/// AuditPlus→Tally override table when one exists for that name.
+fn resolve_account_name(
+    accounts: &Vec<Document>,
+    account_map: &Vec<NameMap>,
+    id: &str,
+) -> Result<String, ExportError> {
+    let account_doc = accounts
+        .iter()
+        .find(|x| x.get_str("id").map(|v| v == id).unwrap_or(false))
+        .ok_or_else(|| ExportError::UnknownAccount(id.to_string()))?;
+    let account_name = account_doc
+        .get_str("name")
+        .map_err(|_| ExportError::UnknownAccount(id.to_string()))?
+        .to_string();
+    if let Some(name) = account_map.iter().find(|x| x.auditplus == account_name) {
+        Ok(name.tally.clone())
+    } else {
+        Ok(account_name)
+    }
+}
+
+pub(crate) fn get_name_map(map_str: String) -> Result<Vec<NameMap>, ExportError> {
     let mut alias: Vec<NameMap> = Vec::new();
     let mut rdr = csv::Reader::from_reader(map_str.as_bytes());
     for result in rdr.deserialize() {
-        let record: NameMap = result.unwrap();
+        let record: NameMap = result?;
         alias.push(record);
     }
-    alias
+    Ok(alias)
 }
 
 fn get_month_dates(from_date: NaiveDate, to_date: NaiveDate) -> Vec<(NaiveDate, NaiveDate)> {
@@ -298,6 +418,23 @@ fn get_query(
                         }
                     }
                 },
+                "stockTrns": {
+                    "$map": {
+                        "input": {
+                            "$filter": {
+                                "input": "$acTrns",
+                                "as": "trn",
+                                "cond": { "$eq": ["$$trn.accountType", "STOCK"] }
+                            }
+                        },
+                        "as": "trn",
+                        "in": {
+                            "account": {"$toString":"$$trn.account"},
+                            "quantity": "$$trn.quantity",
+                            "amount": { "$subtract": ["$$trn.credit", "$$trn.debit"] },
+                        }
+                    }
+                },
                 "rcm": 1,
                 "lut": 1,
                 "description": 1,
@@ -372,186 +509,281 @@ async fn get_voucher_data(
     cash: Option<bool>,
     from_date: NaiveDate,
     to_date: NaiveDate,
-) -> Vec<AGVoucher> {
+) -> Result<Vec<AGVoucher>, ExportError> {
     let query = get_query(collection, cash, from_date, to_date);
     let options = AggregateOptions::builder().allow_disk_use(true).build();
-    let vouchers = db
+    let docs = db
         .collection::<Document>(collection)
         .aggregate(query, options)
-        .await
-        .unwrap()
+        .await?
+        .try_collect::<Vec<Document>>()
+        .await?;
+    let mut vouchers = Vec::with_capacity(docs.len());
+    for doc in docs {
+        vouchers.push(from_document::<AGVoucher>(doc)?);
+    }
+    Ok(vouchers)
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OpeningStock {
+    account: String,
+    #[serde(with = "rust_decimal::serde::float")]
+    quantity: Decimal,
+    #[serde(with = "rust_decimal::serde::float")]
+    amount: Decimal,
+}
+
+/// Load the dated opening-stock snapshot as on `as_on` so the FIFO queues start
+/// from real balances rather than zero.
+async fn get_opening_stock(
+    db: &Database,
+    as_on: NaiveDate,
+) -> Result<Vec<OpeningStock>, ExportError> {
+    let pipeline = vec![
+        doc! {"$match": {"date": {"$lte": as_on.to_string()}}},
+        doc! {"$project": {
+            "_id": 0,
+            "account": {"$toString": "$account"},
+            "quantity": 1,
+            "amount": {"$subtract": ["$debit", "$credit"]},
+        }},
+    ];
+    let options = AggregateOptions::builder().allow_disk_use(true).build();
+    let docs = db
+        .collection::<Document>("openingStock")
+        .aggregate(pipeline, options)
+        .await?
         .try_collect::<Vec<Document>>()
-        .await
-        .unwrap()
+        .await?;
+    Ok(docs
         .into_iter()
-        .filter_map(|x| Some(from_document::<AGVoucher>(x).unwrap()))
-        .collect::<Vec<AGVoucher>>();
-    vouchers
+        .filter_map(|x| from_document::<OpeningStock>(x).ok())
+        .collect())
+}
+
+/// Build a single Tally `Voucher` from an aggregated source voucher, resolving
+/// ledger names and consuming stock lots. Any data-shaped problem (unknown
+/// account, unmapped voucher type, empty party-ledger candidate list) is
+/// returned as an error so the caller can record it and carry on.
+fn build_voucher(
+    voucher: &AGVoucher,
+    accounts: &Vec<Document>,
+    account_map: &Vec<NameMap>,
+    voucher_type_map: &Vec<NameMap>,
+    stock_ledger: &mut StockLedger,
+) -> Result<Voucher, ExportError> {
+    let date = voucher.date.to_string();
+    let voucher_type_name = get_voucher_type(voucher.voucher_type.as_str(), voucher_type_map)?;
+    let voucher_no = voucher.voucher_no.clone();
+    let ref_no = voucher.ref_no.clone();
+    let ref_date = voucher.bill_date.clone();
+    let mut party_ledger_name = String::new();
+    let mut ledger_entries = Vec::new();
+    let mut party_ledgers = Vec::new();
+    for trn in voucher.trns.iter() {
+        let account_name = resolve_account_name(accounts, account_map, &trn.account)?;
+        let amount = trn.amount;
+        let ledger = LedgerEntry::new(account_name.clone(), amount);
+        ledger_entries.push(ledger.clone());
+        if ["Contra", "Receipt"].contains(&voucher_type_name.as_str()) && amount > Decimal::ZERO {
+            party_ledger_name = account_name.clone();
+        }
+        if ["Payment"].contains(&voucher_type_name.as_str()) && amount < Decimal::ZERO {
+            party_ledger_name = account_name.clone();
+        }
+        if [
+            "TRADE_RECEIVABLE",
+            "TRADE_PAYABLE",
+            "ACCOUNT_RECEIVABLE",
+            "ACCOUNT_PAYABLE",
+            "CASH",
+            "BANK_ACCOUNT",
+            "BANK_OD_ACCOUNT",
+            "EFT_ACCOUNT",
+        ]
+        .contains(&trn.account_type.as_str())
+        {
+            let mut party_ledger = ledger.clone();
+            party_ledger.amount = party_ledger.amount.abs();
+            party_ledgers.push(party_ledger);
+        }
+    }
+    let voucher_label = voucher_no.clone().unwrap_or_else(|| date.clone());
+    if ["Journal"].contains(&voucher_type_name.as_str()) {
+        party_ledger_name = ledger_entries
+            .first()
+            .ok_or_else(|| ExportError::EmptyPartyLedger(voucher_label.clone()))?
+            .ledger_name
+            .clone();
+    }
+    if ["Sales", "Purchase", "Credit Note", "Debit Note"].contains(&voucher_type_name.as_str()) {
+        party_ledgers.sort_by(|a, b| cmp_decimal(&b.amount, &a.amount));
+        party_ledger_name = party_ledgers
+            .first()
+            .ok_or_else(|| ExportError::EmptyPartyLedger(voucher_label.clone()))?
+            .ledger_name
+            .clone();
+        party_ledgers.clear();
+    }
+    if !["Journal"].contains(&voucher_type_name.as_str()) {
+        ledger_entries.sort_by(|a, b| cmp_decimal(&b.amount, &a.amount));
+    }
+    // Tally rejects unbalanced vouchers, so the signed legs must net to zero.
+    let sum: Decimal = ledger_entries.iter().map(|e| e.amount).sum();
+    if sum.round_dp(2) != Decimal::ZERO {
+        return Err(ExportError::UnbalancedVoucher {
+            voucher: voucher_label.clone(),
+            sum: format!("{:.2}", sum.round_dp(2)),
+        });
+    }
+    // Resolve every stock leg's name before touching `stock_ledger`: a failure
+    // partway through must not leave earlier legs' FIFO lots/realized-gain
+    // mutations applied for a voucher that ends up excluded from the export.
+    let mut stock_item_names = Vec::with_capacity(voucher.stock_trns.len());
+    for stock_trn in voucher.stock_trns.iter() {
+        stock_item_names.push(resolve_account_name(accounts, account_map, &stock_trn.account)?);
+    }
+    let mut inventory_entries = Vec::with_capacity(voucher.stock_trns.len());
+    for (stock_trn, item_name) in voucher.stock_trns.iter().zip(stock_item_names) {
+        inventory_entries.push(stock_ledger.record(item_name, stock_trn.quantity, stock_trn.amount));
+    }
+    Ok(Voucher::new(
+        date,
+        ref_no,
+        ref_date,
+        voucher_type_name,
+        party_ledger_name,
+        voucher_no,
+        voucher.narration.clone(),
+        ledger_entries,
+        inventory_entries,
+    ))
 }
 
 pub async fn export_data(
     db: &Database,
-    account_map_str: String,
-    voucher_type_map_str: String,
-    from_date: NaiveDate,
-    to_date: NaiveDate,
-) {
-    let account_map = get_name_map(account_map_str);
-    let voucher_type_map = get_name_map(voucher_type_map_str);
+    config: &Config,
+    format: OutputFormat,
+) -> Result<(), ExportError> {
+    let account_map = get_name_map(read_to_string(&config.account_map)?)?;
+    let voucher_type_map = get_name_map(read_to_string(&config.voucher_type_map)?)?;
+    let account_type_map = match &config.account_type_map {
+        Some(path) => get_name_map(read_to_string(path)?)?,
+        None => Vec::new(),
+    };
 
-    let find_options = FindOptions::builder()
-        .projection(doc! {"_id":0,"name":1,"id": {"$toString":"$_id"}})
-        .build();
-    let accounts = db
-        .collection::<Document>("accounts")
-        .find(doc! {}, find_options)
-        .await
-        .unwrap()
-        .try_collect::<Vec<Document>>()
-        .await
-        .unwrap();
-    // let dates = get_dates(from_date, to_date);
-    // let dates = vec![(from_date, to_date)];
-    let dates = vec![
-        (
-            NaiveDate::from_ymd(2022, 4, 1),
-            NaiveDate::from_ymd(2022, 4, 30),
-        ),
-        (
-            NaiveDate::from_ymd(2022, 5, 1),
-            NaiveDate::from_ymd(2022, 5, 31),
-        ),
-        // (
-        //     NaiveDate::from_ymd(2022, 6, 1),
-        //     NaiveDate::from_ymd(2022, 6, 30),
-        // ),
-        // (
-        //     NaiveDate::from_ymd(2022, 7, 1),
-        //     NaiveDate::from_ymd(2022, 7, 31),
-        // ),
-        // (
-        //     NaiveDate::from_ymd(2022, 8, 1),
-        //     NaiveDate::from_ymd(2022, 8, 31),
-        // ),
-        // (
-        //     NaiveDate::from_ymd(2022, 9, 1),
-        //     NaiveDate::from_ymd(2022, 9, 30),
-        // ),
-        // (
-        //     NaiveDate::from_ymd(2022, 10, 1),
-        //     NaiveDate::from_ymd(2022, 10, 31),
-        // ),
-        // (
-        //     NaiveDate::from_ymd(2022, 11, 1),
-        //     NaiveDate::from_ymd(2022, 11, 30),
-        // ),
-        // (
-        //     NaiveDate::from_ymd(2022, 12, 1),
-        //     NaiveDate::from_ymd(2022, 12, 31),
-        // ),
-        // (
-        //     NaiveDate::from_ymd(2023, 01, 1),
-        //     NaiveDate::from_ymd(2023, 01, 31),
-        // ),
-        // (
-        //     NaiveDate::from_ymd(2023, 02, 1),
-        //     NaiveDate::from_ymd(2023, 02, 28),
-        // ),
-        // (
-        //     NaiveDate::from_ymd(2023, 03, 1),
-        //     NaiveDate::from_ymd(2023, 03, 31),
-        // ),
-    ];
+    // Stream the chart of accounts in `_id`-ordered pages, each resumed from
+    // the previous page's last `_id`; the `since` filter and the page size
+    // come from the config file rather than being hardcoded.
+    let export_options = ExportOptions {
+        since: config
+            .since
+            .map(|d| DateTime::from_chrono(Utc.from_utc_datetime(&d.and_time(NaiveTime::from_hms(0, 0, 0))))),
+        page_size: config.page_size,
+        cursor: None,
+    };
+    let mut accounts = Vec::new();
+    let mut cursor = export_options.cursor;
+    loop {
+        let page_opts = ExportOptions {
+            cursor,
+            ..export_options.clone()
+        };
+        let (page, next) = get_accounts(db, &page_opts).await?;
+        let page_len = page.len() as i64;
+        accounts.extend(page);
+        match (next, export_options.page_size) {
+            (Some(n), Some(size)) if page_len == size => cursor = Some(n),
+            _ => break,
+        }
+    }
+    // The `LEDGER` masters are a snapshot of the whole chart of accounts, not
+    // scoped to a date range, so they're built and written once up front.
+    // Tally's own XML is the only backend here that models a ledger master
+    // (the plain-text and ODS backends only describe vouchers), so that's
+    // the only format that writes one out.
+    if format == OutputFormat::TallyXml {
+        let ledger_messages =
+            crate::ledger_master::export_ledger(db, &account_type_map, &account_map, &export_options)
+                .await?;
+        let req_data = crate::ledger_master::LedgerRequestData::new(ledger_messages);
+        let imp_data = crate::ledger_master::LedgerImportData::new(req_data);
+        let body = crate::ledger_master::LedgerBody::new(imp_data);
+        let env = crate::ledger_master::LedgerEnvelope::new(body);
+        let data = crate::ledger_master::LedgerExportData::new(env);
+        let options = xml_serde::Options {
+            include_schema_location: false,
+        };
+        let res = xml_serde::to_string_custom(&data, options)
+            .map_err(|e| ExportError::Xml(e.to_string()))?;
+        let out_path = config.output_dir.join("tally_ledgers.xml");
+        let mut file = File::create(out_path)?;
+        file.write_all(res.as_bytes())?;
+    }
+
+    let dates = config.date_ranges();
+
+    // FIFO cost-basis engine, seeded from the opening snapshot as on the first
+    // range start and carried across every range so lots flow chronologically.
+    let mut stock_ledger = StockLedger::new();
+    if let Some(first) = dates.first() {
+        for opening in get_opening_stock(db, first.from).await? {
+            // An unmapped opening item is skipped rather than aborting the run.
+            if let Ok(item_name) = resolve_account_name(&accounts, &account_map, &opening.account) {
+                stock_ledger.seed_opening(item_name, opening.quantity, opening.amount);
+            }
+        }
+    }
+
+    // Per-voucher failures are collected here and reported at the end instead
+    // of aborting the whole export.
+    let mut failures: Vec<(String, ExportError)> = Vec::new();
+    let mut gst_warnings = Vec::new();
     for dt in dates {
-        println!("\n{:?}\n**********", &dt.0);
+        println!("\n{:?}\n**********", &dt.from);
         let mut tally_messages = Vec::new();
-        // let collections = vec!["vouchers", "sales", "purchases", "gst_vouchers"];
-        let collections = vec!["sales"];
-        for collection in collections {
-            let vouchers = if collection == "sales" {
+        for collection in config.collections.iter() {
+            let collection = collection.as_str();
+            let mut vouchers = if collection == "sales" {
                 // cash_sale
-                let cash_sale = get_voucher_data(db, collection, Some(true), dt.0, dt.1).await;
+                let cash_sale = get_voucher_data(db, collection, Some(true), dt.from, dt.to).await?;
                 println!("cash only sale: {:?}", cash_sale.len());
                 // credit_sale
-                let credit_sale = get_voucher_data(db, collection, None, dt.0, dt.1).await;
+                let credit_sale = get_voucher_data(db, collection, None, dt.from, dt.to).await?;
                 println!("cash & credit sale:{:?}", credit_sale.len());
                 [cash_sale.to_vec(), credit_sale.to_vec()].concat()
             } else {
-                get_voucher_data(db, collection, None, dt.0, dt.1).await
+                get_voucher_data(db, collection, None, dt.from, dt.to).await?
             };
+            // Stock lots must be consumed in date order for FIFO to be exact.
+            vouchers.sort_by(|a, b| a.date.cmp(&b.date));
             println!("{}: {:?}", collection, vouchers.len());
             for voucher in vouchers.iter() {
-                let date = voucher.date.to_string();
-                let voucher_type_name =
-                    get_voucher_type(voucher.voucher_type.as_str(), &voucher_type_map);
-                let voucher_no = voucher.voucher_no.clone();
-                let ref_no = voucher.ref_no.clone();
-                let ref_date = voucher.bill_date.clone();
-                let mut party_ledger_name = String::new();
-                let mut ledger_entries = Vec::new();
-                let mut party_ledgers = Vec::new();
-                for trn in voucher.trns.iter() {
-                    let account_doc = accounts
-                        .iter()
-                        .find(|x| x.get_str("id").unwrap() == trn.account)
-                        .unwrap();
-                    let account_name = account_doc.get_str("name").unwrap().to_string();
-                    let account_name = if let Some(name) =
-                        account_map.iter().find(|x| x.auditplus == account_name)
-                    {
-                        name.tally.clone()
-                    } else {
-                        account_name
-                    };
-                    let amount = trn.amount as f64;
-                    let ledger = LedgerEntry::new(account_name.clone(), amount);
-                    ledger_entries.push(ledger.clone());
-                    if ["Contra", "Receipt"].contains(&voucher_type_name.as_str()) && amount > 0.0 {
-                        party_ledger_name = account_name.clone();
-                    }
-                    if ["Payment"].contains(&voucher_type_name.as_str()) && amount < 0.0 {
-                        party_ledger_name = account_name.clone();
+                let voucher_ref = voucher.voucher_no.clone().unwrap_or_default();
+                let built = build_voucher(
+                    voucher,
+                    &accounts,
+                    &account_map,
+                    &voucher_type_map,
+                    &mut stock_ledger,
+                );
+                let mut tally_voucher = match built {
+                    Ok(v) => v,
+                    Err(e) => {
+                        failures.push((voucher_ref, e));
+                        continue;
                     }
-                    if [
-                        "TRADE_RECEIVABLE",
-                        "TRADE_PAYABLE",
-                        "ACCOUNT_RECEIVABLE",
-                        "ACCOUNT_PAYABLE",
-                        "CASH",
-                        "BANK_ACCOUNT",
-                        "BANK_OD_ACCOUNT",
-                        "EFT_ACCOUNT",
-                    ]
-                    .contains(&trn.account_type.as_str())
+                };
+                if let Some(gst_cfg) = config.gst.as_ref() {
+                    if let Some(warning) =
+                        crate::gst::apply(&mut tally_voucher, voucher.rcm, voucher.lut, gst_cfg)
                     {
-                        let mut party_ledger = ledger.clone();
-                        party_ledger.amount = party_ledger.amount.abs();
-                        party_ledgers.push(party_ledger);
+                        gst_warnings.push(warning);
                     }
                 }
-                if ["Journal"].contains(&voucher_type_name.as_str()) {
-                    party_ledger_name = ledger_entries.first().clone().unwrap().ledger_name.clone();
-                }
-                if ["Sales", "Purchase", "Credit Note", "Debit Note"]
-                    .contains(&voucher_type_name.as_str())
-                {
-                    party_ledgers.sort_by(|a, b| cmp_f64(&b.amount, &a.amount));
-                    party_ledger_name = party_ledgers.first().clone().unwrap().ledger_name.clone();
-                    party_ledgers.clear();
-                }
-                if !["Journal"].contains(&voucher_type_name.as_str()) {
-                    ledger_entries.sort_by(|a, b| cmp_f64(&b.amount, &a.amount));
-                }
-                let voucher = Voucher::new(
-                    date,
-                    ref_no,
-                    ref_date,
-                    voucher_type_name,
-                    party_ledger_name,
-                    voucher_no,
-                    ledger_entries,
-                );
-                let tally_message = TallyMessage::new(voucher);
+                let tally_message = TallyMessage::new(tally_voucher);
                 tally_messages.push(tally_message);
             }
         }
@@ -560,11 +792,103 @@ pub async fn export_data(
         let body = Body::new(imp_data);
         let env = Envelope::new(body);
         let data = ExportData::new(env);
-        let options = xml_serde::Options {
-            include_schema_location: false,
-        };
-        let res = xml_serde::to_string_custom(&data, options).unwrap();
-        let mut file = File::create(format!("tally_data-{}.xml", dt.0.to_string())).unwrap();
-        file.write_all(res.as_bytes()).unwrap();
+        match format {
+            OutputFormat::TallyXml => {
+                let options = xml_serde::Options {
+                    include_schema_location: false,
+                };
+                let res = xml_serde::to_string_custom(&data, options)
+                    .map_err(|e| ExportError::Xml(e.to_string()))?;
+                let out_path = config.output_dir.join(format!("tally_data-{}.xml", dt.name));
+                let mut file = File::create(out_path)?;
+                file.write_all(res.as_bytes())?;
+            }
+            OutputFormat::Ledger => {
+                let res = crate::ledger::to_ledger(&data);
+                let out_path = config.output_dir.join(format!("tally_data-{}.ledger", dt.name));
+                let mut file = File::create(out_path)?;
+                file.write_all(res.as_bytes())?;
+            }
+            OutputFormat::Ods => {
+                let out_path = config.output_dir.join(format!("tally_data-{}.ods", dt.name));
+                crate::ods::write_ods(&data, &out_path).map_err(|e| ExportError::Ods(e.to_string()))?;
+            }
+        }
+    }
+
+    // Per-voucher failures that were skipped so the rest could export.
+    if !failures.is_empty() {
+        let mut report = String::from("voucher,error\n");
+        for (voucher_ref, err) in failures.iter() {
+            report.push_str(&format!("{},{}\n", voucher_ref, err));
+        }
+        let report_path = config.output_dir.join("export_errors.csv");
+        let mut file = File::create(report_path)?;
+        file.write_all(report.as_bytes())?;
+    }
+
+    // GST reconciliation warnings gathered across the run.
+    if !gst_warnings.is_empty() {
+        let mut report = String::from("voucher_no,warning\n");
+        for warning in gst_warnings.iter() {
+            report.push_str(&format!("{},{}\n", warning.voucher_no, warning.message));
+        }
+        let report_path = config.output_dir.join("gst_warnings.csv");
+        let mut file = File::create(report_path)?;
+        file.write_all(report.as_bytes())?;
+    }
+
+    // Per-item realized-gain report for the whole run.
+    let realized = stock_ledger.realized();
+    if !realized.is_empty() {
+        let mut report = String::from("item,sale_value,cost_basis,realized_gain,shortfall_qty\n");
+        let mut items = realized.iter().collect::<Vec<_>>();
+        items.sort_by(|a, b| a.0.cmp(b.0));
+        for (item, gain) in items {
+            report.push_str(&format!(
+                "{},{:.2},{:.2},{:.2},{}\n",
+                item,
+                gain.sale_value.round_dp(2),
+                gain.cost_basis.round_dp(2),
+                gain.realized_gain.round_dp(2),
+                gain.shortfall_qty,
+            ));
+        }
+        let report_path = config.output_dir.join("realized_gain.csv");
+        let mut file = File::create(report_path)?;
+        file.write_all(report.as_bytes())?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn amount_rounding_is_half_to_even_at_two_dp() {
+        // `serialize_amount` rounds via `round_dp`, which is banker's
+        // rounding: a trailing 5 rounds to the nearest even cent rather than
+        // always up, so these two cases land on opposite sides.
+        assert_eq!(Decimal::new(12345, 3).round_dp(2), Decimal::new(1234, 2));
+        assert_eq!(Decimal::new(12355, 3).round_dp(2), Decimal::new(1236, 2));
+    }
+
+    #[test]
+    fn tally_date_strips_dashes_without_touching_the_stored_value() {
+        let voucher = Voucher::new(
+            "2024-01-05".to_string(),
+            None,
+            None,
+            "Sales".to_string(),
+            "Party".to_string(),
+            None,
+            None,
+            vec![],
+            vec![],
+        );
+        assert_eq!(voucher.date, "2024-01-05");
+        assert_eq!(voucher.date.replace('-', ""), "20240105");
     }
 }
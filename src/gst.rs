@@ -0,0 +1,237 @@
+use rust_decimal::Decimal;
+
+use crate::config::GstConfig;
+use crate::export::{LedgerEntry, Voucher};
+
+/// A per-voucher reconciliation warning: the recorded tax legs diverge from the
+/// tax computed off the taxable value beyond the configured tolerance.
+#[derive(Debug, Clone)]
+pub struct GstWarning {
+    pub voucher_no: String,
+    pub message: String,
+}
+
+fn sum_matching(entries: &[LedgerEntry], ledgers: &[String]) -> Decimal {
+    entries
+        .iter()
+        .filter(|e| ledgers.iter().any(|l| l == &e.ledger_name))
+        .map(|e| e.amount.abs())
+        .sum()
+}
+
+/// Apply the GST treatment for a voucher's `rcm`/`lut` flags and reconcile the
+/// recorded tax against the tax implied by the taxable value. Mutates the
+/// voucher (reverse-charge split + flag, or zero-rating) and returns a warning
+/// when the figures diverge beyond `cfg.tolerance`.
+pub fn apply(
+    voucher: &mut Voucher,
+    rcm: Option<bool>,
+    lut: Option<bool>,
+    cfg: &GstConfig,
+) -> Option<GstWarning> {
+    let cgst = sum_matching(&voucher.ledger_entries, &cfg.cgst_ledgers);
+    let sgst = sum_matching(&voucher.ledger_entries, &cfg.sgst_ledgers);
+    let igst = sum_matching(&voucher.ledger_entries, &cfg.igst_ledgers);
+    let tax_total = cgst + sgst + igst;
+
+    // LUT / zero-rated export: taxable value stays, output tax is nil.
+    if lut.unwrap_or(false) {
+        zero_rate(voucher, cfg);
+        return None;
+    }
+
+    // Reverse charge: the supplier raises no tax, so we book the liability on
+    // the payable ledger and claim the matching credit on the creditable one.
+    if rcm.unwrap_or(false) {
+        voucher.is_reverse_charge = Some("Yes".to_string());
+        if let (Some(payable), Some(creditable)) =
+            (&cfg.rcm_payable_ledger, &cfg.rcm_creditable_ledger)
+        {
+            if tax_total > Decimal::ZERO {
+                voucher
+                    .ledger_entries
+                    .push(LedgerEntry::new(payable.clone(), tax_total));
+                voucher
+                    .ledger_entries
+                    .push(LedgerEntry::new(creditable.clone(), -tax_total));
+            }
+        }
+    }
+
+    reconcile(voucher, cgst, sgst, igst, cfg)
+}
+
+/// Zero every tax leg and push the removed amount onto the taxable-value leg
+/// (the largest non-party entry) so the voucher still nets to zero after the
+/// tax is dropped, instead of leaving it unbalanced past `build_voucher`'s
+/// balance check.
+fn zero_rate(voucher: &mut Voucher, cfg: &GstConfig) {
+    let mut removed = Decimal::ZERO;
+    for entry in voucher.ledger_entries.iter_mut() {
+        let is_tax = cfg.cgst_ledgers.iter().any(|l| l == &entry.ledger_name)
+            || cfg.sgst_ledgers.iter().any(|l| l == &entry.ledger_name)
+            || cfg.igst_ledgers.iter().any(|l| l == &entry.ledger_name);
+        if is_tax {
+            removed += entry.amount;
+            entry.amount = Decimal::ZERO;
+        }
+    }
+    if removed == Decimal::ZERO {
+        return;
+    }
+    let party_ledger = voucher.party_ledger.clone();
+    let taxable_leg = voucher
+        .ledger_entries
+        .iter_mut()
+        .filter(|e| e.ledger_name != party_ledger)
+        .max_by(|a, b| a.amount.abs().cmp(&b.amount.abs()));
+    if let Some(entry) = taxable_leg {
+        entry.amount += removed;
+        entry.is_deemed_positive = if entry.amount < Decimal::ZERO {
+            "Yes".to_string()
+        } else {
+            "No".to_string()
+        };
+    }
+}
+
+fn reconcile(
+    voucher: &Voucher,
+    cgst: Decimal,
+    sgst: Decimal,
+    igst: Decimal,
+    cfg: &GstConfig,
+) -> Option<GstWarning> {
+    let voucher_no = voucher.voucher_no.clone().unwrap_or_default();
+    let tax_total = cgst + sgst + igst;
+    if tax_total <= Decimal::ZERO {
+        return None;
+    }
+    // Intra-state CGST and SGST halves must match.
+    if igst == Decimal::ZERO && (cgst - sgst).abs() > cfg.tolerance {
+        return Some(GstWarning {
+            voucher_no,
+            message: format!("CGST {} does not match SGST {}", cgst, sgst),
+        });
+    }
+    // The rate-match check below runs only for Sales and Purchase vouchers;
+    // every other voucher type, Credit Note and Debit Note included, gets no
+    // rate warning.
+    if !["Sales", "Purchase"].contains(&voucher.voucher_type.as_str()) {
+        return None;
+    }
+    // Taxable value is the party/value legs net of the tax already posted.
+    let gross: Decimal = voucher
+        .ledger_entries
+        .iter()
+        .map(|e| e.amount)
+        .filter(|a| *a > Decimal::ZERO)
+        .sum();
+    let taxable = gross - tax_total;
+    if taxable <= Decimal::ZERO {
+        return None;
+    }
+    // The implied rate must land on one of the configured rates, and the tax
+    // computed from it must reconcile with what was posted.
+    let implied = tax_total / taxable * Decimal::new(100, 0);
+    let matched = cfg
+        .rates
+        .iter()
+        .find(|r| (implied - **r).abs() <= Decimal::new(5, 1));
+    match matched {
+        Some(rate) => {
+            let expected = (taxable * *rate / Decimal::new(100, 0)).round_dp(2);
+            if (expected - tax_total).abs() > cfg.tolerance {
+                Some(GstWarning {
+                    voucher_no,
+                    message: format!(
+                        "tax {} at {}% expected {} on taxable {}",
+                        tax_total, rate, expected, taxable
+                    ),
+                })
+            } else {
+                None
+            }
+        }
+        None => Some(GstWarning {
+            voucher_no,
+            message: format!("implied rate {:.2}% matches no configured rate", implied),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cfg() -> GstConfig {
+        GstConfig {
+            rates: vec![Decimal::new(18, 0)],
+            cgst_ledgers: vec!["CGST".to_string()],
+            sgst_ledgers: vec!["SGST".to_string()],
+            igst_ledgers: vec![],
+            rcm_payable_ledger: None,
+            rcm_creditable_ledger: None,
+            tolerance: Decimal::new(1, 0),
+        }
+    }
+
+    fn sales_voucher(gross: Decimal) -> Voucher {
+        Voucher::new(
+            "2024-01-01".to_string(),
+            None,
+            None,
+            "Sales".to_string(),
+            "Party".to_string(),
+            Some("1".to_string()),
+            None,
+            vec![LedgerEntry::new("Party".to_string(), gross)],
+            vec![],
+        )
+    }
+
+    #[test]
+    fn reconcile_is_silent_within_tolerance() {
+        let voucher = sales_voucher(Decimal::new(1180, 0));
+        let warning = reconcile(
+            &voucher,
+            Decimal::new(90, 0),
+            Decimal::new(90, 0),
+            Decimal::ZERO,
+            &cfg(),
+        );
+        assert!(warning.is_none());
+    }
+
+    #[test]
+    fn reconcile_warns_just_past_tolerance() {
+        // Taxable is still 1000 and the implied 18.2% matches the configured
+        // 18%, but the posted tax (182) is 2 away from the expected 180 — past
+        // the tolerance of 1.
+        let voucher = sales_voucher(Decimal::new(1182, 0));
+        let warning = reconcile(
+            &voucher,
+            Decimal::new(91, 0),
+            Decimal::new(91, 0),
+            Decimal::ZERO,
+            &cfg(),
+        );
+        assert!(warning.is_some());
+    }
+
+    #[test]
+    fn reconcile_skips_credit_note_gross_sign_assumption() {
+        // Same inputs as the warning case above, but the rate check runs only
+        // for Sales and Purchase, so a Credit Note gets no warning.
+        let mut voucher = sales_voucher(Decimal::new(1182, 0));
+        voucher.voucher_type = "Credit Note".to_string();
+        let warning = reconcile(
+            &voucher,
+            Decimal::new(91, 0),
+            Decimal::new(91, 0),
+            Decimal::ZERO,
+            &cfg(),
+        );
+        assert!(warning.is_none());
+    }
+}
@@ -0,0 +1,37 @@
+use crate::export::{ExportData, Voucher};
+
+/// Serialize an export tree into the plain-text double-entry journal format
+/// consumed by ledger-cli/hledger: one entry per voucher, headed by the date
+/// and party ledger, with two-space-indented postings for each ledger line.
+pub fn to_ledger(data: &ExportData) -> String {
+    let mut out = String::new();
+    for message in data.envelope.body.import_data.request_data.items.iter() {
+        for voucher in message.items.iter() {
+            out.push_str(&format_voucher(voucher));
+            out.push('\n');
+        }
+    }
+    out
+}
+
+fn format_voucher(voucher: &Voucher) -> String {
+    let date = voucher.date.replace('-', "/");
+    let mut entry = format!("{} {}", date, voucher.party_ledger);
+    if let Some(ref_no) = voucher.ref_no.as_ref() {
+        entry.push_str(&format!("  ; ref:{}", ref_no));
+    }
+    entry.push('\n');
+    // The stored amount is Tally's (negative for a debit). Plain-text ledgers
+    // take debits as positive, so the sign is flipped. The last posting's
+    // amount is left blank so the reader's tools infer it and the entry
+    // balances to zero.
+    let last = voucher.ledger_entries.len().saturating_sub(1);
+    for (i, line) in voucher.ledger_entries.iter().enumerate() {
+        if i == last {
+            entry.push_str(&format!("  {}\n", line.ledger_name));
+        } else {
+            entry.push_str(&format!("  {}    {:.2}\n", line.ledger_name, -line.amount));
+        }
+    }
+    entry
+}
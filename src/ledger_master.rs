@@ -0,0 +1,299 @@
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, from_document, oid::ObjectId, Document},
+    options::FindOptions,
+    Database,
+};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::error::ExportError;
+use crate::export::{serialize_amount, ExportOptions, NameMap};
+
+/// Tally keeps debit-nature balances positive and credit-nature ones negative.
+fn is_debit_nature(group: &str) -> bool {
+    matches!(
+        group,
+        "Sundry Debtors"
+            | "Cash-in-Hand"
+            | "Bank Accounts"
+            | "Stock-in-Hand"
+            | "Direct Expenses"
+            | "Indirect Expenses"
+    )
+}
+
+/// Map an AuditPlus account type to its canonical Tally group. The override
+/// table (loaded through the same `NameMap` mechanism as the ledger-name
+/// overrides) wins; otherwise the built-in defaults apply. An unrecognized
+/// type is an error naming the offending value rather than a panic.
+pub fn get_account_type(
+    account_type: &str,
+    account_type_map: &[NameMap],
+) -> Result<String, ExportError> {
+    if let Some(entry) = account_type_map.iter().find(|x| x.auditplus == account_type) {
+        return Ok(entry.tally.clone());
+    }
+    let group = match account_type {
+        "TRADE_RECEIVABLE" | "ACCOUNT_RECEIVABLE" => "Sundry Debtors",
+        "TRADE_PAYABLE" | "ACCOUNT_PAYABLE" => "Sundry Creditors",
+        "CASH" => "Cash-in-Hand",
+        "BANK_ACCOUNT" | "EFT_ACCOUNT" => "Bank Accounts",
+        "BANK_OD_ACCOUNT" => "Bank OD A/c",
+        "GST_PAYABLE" => "Duties & Taxes",
+        "STOCK" => "Stock-in-Hand",
+        "DIRECT_INCOME" => "Direct Incomes",
+        "INDIRECT_INCOME" => "Indirect Incomes",
+        "DIRECT_EXPENSE" => "Direct Expenses",
+        "INDIRECT_EXPENSE" => "Indirect Expenses",
+        other => return Err(ExportError::UnknownAccountType(other.to_string())),
+    };
+    Ok(group.to_string())
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+struct NameList {
+    #[serde(rename = "NAME")]
+    names: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+struct LanguageName {
+    #[serde(rename = "NAME.LIST")]
+    name_list: NameList,
+    #[serde(rename = "LANGUAGEID")]
+    language_id: i64,
+}
+
+/// Bank-account details emitted for bank-type ledgers.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+struct BankDetails {
+    #[serde(rename = "IFSCODE")]
+    ifsc_code: String,
+    #[serde(rename = "ACCOUNTNUMBER")]
+    account_number: String,
+}
+
+/// A source account document as stored in Mongo. The balance, currency and
+/// bank fields are only present on documents that carry them.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CAccount {
+    pub name: String,
+    pub account_type: String,
+    #[serde(default, with = "rust_decimal::serde::float_option")]
+    pub opening_balance: Option<Decimal>,
+    #[serde(default)]
+    pub currency_name: Option<String>,
+    #[serde(default)]
+    pub ifsc_code: Option<String>,
+    #[serde(default)]
+    pub account_number: Option<String>,
+}
+
+/// A Tally `LEDGER` master.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub struct Account {
+    #[serde(rename = "NAME")]
+    name: String,
+    parent: String,
+    #[serde(
+        rename = "OPENINGBALANCE",
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_opening_balance"
+    )]
+    opening_balance: Option<Decimal>,
+    #[serde(rename = "CURRENCYNAME", skip_serializing_if = "Option::is_none")]
+    currency_name: Option<String>,
+    #[serde(rename = "BANKDETAILS", skip_serializing_if = "Option::is_none")]
+    bank_details: Option<BankDetails>,
+    #[serde(rename = "LANGUAGENAME.LIST")]
+    language_name: LanguageName,
+}
+
+fn serialize_opening_balance<S>(
+    amount: &Option<Decimal>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    // Only reached when the field is present (see skip_serializing_if).
+    serialize_amount(amount.as_ref().unwrap(), serializer)
+}
+
+impl Account {
+    /// Build a `LEDGER` master, resolving `account.name` through the same
+    /// AuditPlus→Tally override table (`account_map`) that
+    /// `export::resolve_account_name` applies to every voucher's ledger
+    /// entries, so the master and the vouchers referencing it agree on a name.
+    fn from_source(account: CAccount, parent: String, account_map: &[NameMap]) -> Self {
+        let name = match account_map.iter().find(|x| x.auditplus == account.name) {
+            Some(entry) => entry.tally.clone(),
+            None => account.name.clone(),
+        };
+        let language_name = LanguageName {
+            name_list: NameList {
+                names: vec![name.clone()],
+            },
+            language_id: 1033,
+        };
+        // Dr positive / Cr negative depending on the resolved group.
+        let opening_balance = account.opening_balance.map(|bal| {
+            if is_debit_nature(&parent) {
+                bal.abs()
+            } else {
+                -bal.abs()
+            }
+        });
+        let bank_details = match (account.ifsc_code, account.account_number) {
+            (Some(ifsc_code), Some(account_number)) => Some(BankDetails {
+                ifsc_code,
+                account_number,
+            }),
+            _ => None,
+        };
+        Self {
+            name,
+            parent,
+            opening_balance,
+            currency_name: account.currency_name,
+            bank_details,
+            language_name,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub struct LedgerMessage {
+    #[serde(rename = "LEDGER")]
+    pub ledger: Account,
+}
+
+// The master-data envelope mirrors `export::ExportData`'s Envelope/Body/
+// ImportData/RequestData nesting, just wrapping `LedgerMessage` instead of
+// the voucher `TallyMessage`.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub struct LedgerExportData {
+    pub envelope: LedgerEnvelope,
+}
+impl LedgerExportData {
+    pub fn new(envelope: LedgerEnvelope) -> Self {
+        Self { envelope }
+    }
+}
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub struct LedgerEnvelope {
+    pub body: LedgerBody,
+}
+impl LedgerEnvelope {
+    pub fn new(body: LedgerBody) -> Self {
+        Self { body }
+    }
+}
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub struct LedgerBody {
+    #[serde(rename = "IMPORTDATA")]
+    pub import_data: LedgerImportData,
+}
+impl LedgerBody {
+    pub fn new(import_data: LedgerImportData) -> Self {
+        Self { import_data }
+    }
+}
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub struct LedgerImportData {
+    #[serde(rename = "REQUESTDATA")]
+    pub request_data: LedgerRequestData,
+}
+impl LedgerImportData {
+    pub fn new(request_data: LedgerRequestData) -> Self {
+        Self { request_data }
+    }
+}
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub struct LedgerRequestData {
+    #[serde(rename = "TALLYMESSAGE")]
+    pub items: Vec<LedgerMessage>,
+}
+impl LedgerRequestData {
+    pub fn new(items: Vec<LedgerMessage>) -> Self {
+        Self { items }
+    }
+}
+
+/// Fetch one page of accounts honoring `opts`, mirroring
+/// `export::get_accounts`, and return the page and the `_id` cursor to resume
+/// from.
+async fn get_account_docs(
+    db: &Database,
+    opts: &ExportOptions,
+) -> Result<(Vec<Document>, Option<ObjectId>), ExportError> {
+    let mut filter = doc! {};
+    if let Some(since) = opts.since {
+        filter.insert("updatedAt", doc! {"$gt": since});
+    }
+    if let Some(cursor) = opts.cursor {
+        filter.insert("_id", doc! {"$gt": cursor});
+    }
+    let mut builder = FindOptions::builder().sort(doc! {"_id": 1});
+    if let Some(page_size) = opts.page_size {
+        builder = builder.limit(page_size);
+    }
+    let docs = db
+        .collection::<Document>("accounts")
+        .find(filter, builder.build())
+        .await?
+        .try_collect::<Vec<Document>>()
+        .await?;
+    let next = docs.last().and_then(|d| d.get_object_id("_id").ok());
+    Ok((docs, next))
+}
+
+/// Build the `LEDGER` masters for the chart of accounts, resolving each
+/// account's Tally group through `get_account_type` and its ledger *name*
+/// through `account_map` — the same override table `export::resolve_account_name`
+/// applies to voucher entries, so the master and the vouchers agree on a name.
+/// Streams the source collection in `_id`-ordered pages per `opts` so a delta
+/// sync can resume from a cursor / `since` timestamp instead of re-pulling
+/// every account. Propagates an error for an unrecognized account type
+/// instead of aborting the process.
+pub async fn export_ledger(
+    db: &Database,
+    account_type_map: &[NameMap],
+    account_map: &[NameMap],
+    opts: &ExportOptions,
+) -> Result<Vec<LedgerMessage>, ExportError> {
+    let mut messages = Vec::new();
+    let mut cursor = opts.cursor;
+    loop {
+        let page_opts = ExportOptions {
+            cursor,
+            ..opts.clone()
+        };
+        let (docs, next) = get_account_docs(db, &page_opts).await?;
+        let page_len = docs.len() as i64;
+        for doc in docs {
+            let account: CAccount = from_document(doc)?;
+            let parent = get_account_type(&account.account_type, account_type_map)?;
+            messages.push(LedgerMessage {
+                ledger: Account::from_source(account, parent, account_map),
+            });
+        }
+        match (next, opts.page_size) {
+            (Some(n), Some(size)) if page_len == size => cursor = Some(n),
+            _ => break,
+        }
+    }
+    Ok(messages)
+}
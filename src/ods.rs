@@ -0,0 +1,79 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+use spreadsheet_ods::{Sheet, WorkBook};
+
+use crate::export::ExportData;
+
+fn to_f64(amount: Decimal) -> f64 {
+    amount.round_dp(2).to_f64().unwrap_or_default()
+}
+
+/// Write an `.ods` workbook: one sheet per voucher type listing each voucher's
+/// header and ledger lines, plus a "Trial Balance" sheet that groups every
+/// ledger line by name and sums the debit/credit columns across the run.
+pub fn write_ods(data: &ExportData, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let mut wb = WorkBook::new_empty();
+
+    // One sheet per voucher type, keyed so the order is stable across runs.
+    let mut sheets: BTreeMap<String, Sheet> = BTreeMap::new();
+    let mut next_row: BTreeMap<String, u32> = BTreeMap::new();
+    // Running trial-balance totals, Dr/Cr per ledger.
+    let mut trial: BTreeMap<String, (Decimal, Decimal)> = BTreeMap::new();
+
+    for message in data.envelope.body.import_data.request_data.items.iter() {
+        for voucher in message.items.iter() {
+            let sheet = sheets.entry(voucher.voucher_type.clone()).or_insert_with(|| {
+                let mut sheet = Sheet::new(&voucher.voucher_type);
+                for (col, head) in ["Date", "Voucher No", "Party Ledger", "Reference"]
+                    .iter()
+                    .enumerate()
+                {
+                    sheet.set_value(0, col as u32, head.to_string());
+                }
+                sheet
+            });
+            let row = *next_row.entry(voucher.voucher_type.clone()).or_insert(1);
+            sheet.set_value(row, 0, voucher.date.clone());
+            sheet.set_value(row, 1, voucher.voucher_no.clone().unwrap_or_default());
+            sheet.set_value(row, 2, voucher.party_ledger.clone());
+            sheet.set_value(row, 3, voucher.ref_no.clone().unwrap_or_default());
+            // Ledger lines appended as (name, amount) column pairs.
+            let mut col = 4u32;
+            for line in voucher.ledger_entries.iter() {
+                sheet.set_value(row, col, line.ledger_name.clone());
+                sheet.set_value(row, col + 1, to_f64(line.amount));
+                col += 2;
+                let entry = trial.entry(line.ledger_name.clone()).or_default();
+                if line.amount < Decimal::ZERO {
+                    entry.0 += line.amount.abs();
+                } else {
+                    entry.1 += line.amount;
+                }
+            }
+            next_row.insert(voucher.voucher_type.clone(), row + 1);
+        }
+    }
+
+    for (_, sheet) in sheets {
+        wb.push_sheet(sheet);
+    }
+
+    let mut tb = Sheet::new("Trial Balance");
+    for (col, head) in ["Ledger", "Debit", "Credit"].iter().enumerate() {
+        tb.set_value(0, col as u32, head.to_string());
+    }
+    let mut row = 1u32;
+    for (ledger, (debit, credit)) in trial {
+        tb.set_value(row, 0, ledger);
+        tb.set_value(row, 1, to_f64(debit));
+        tb.set_value(row, 2, to_f64(credit));
+        row += 1;
+    }
+    wb.push_sheet(tb);
+
+    spreadsheet_ods::write_ods(&mut wb, path)?;
+    Ok(())
+}
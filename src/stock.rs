@@ -0,0 +1,219 @@
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+
+use crate::export::serialize_amount;
+
+/// A single FIFO lot: `quantity` units acquired at `unit_cost` each.
+#[derive(Debug, Clone)]
+struct Lot {
+    quantity: Decimal,
+    unit_cost: Decimal,
+}
+
+/// Per-item FIFO queue. Purchases push lots onto the back; sales consume from
+/// the front, so the oldest cost is realized first.
+#[derive(Debug, Default)]
+struct ItemFifo {
+    lots: VecDeque<Lot>,
+}
+
+/// The outcome of consuming quantity from an item's queue.
+#[derive(Debug, Clone)]
+pub struct Consumption {
+    pub quantity: Decimal,
+    pub cost_basis: Decimal,
+    /// Quantity sold for which no lot was available (clamped to zero cost).
+    pub shortfall: Decimal,
+}
+
+impl ItemFifo {
+    fn push(&mut self, quantity: Decimal, unit_cost: Decimal) {
+        if quantity > Decimal::ZERO {
+            self.lots.push_back(Lot {
+                quantity,
+                unit_cost,
+            });
+        }
+    }
+
+    fn consume(&mut self, mut quantity: Decimal) -> Consumption {
+        let requested = quantity;
+        let mut cost_basis = Decimal::ZERO;
+        while quantity > Decimal::ZERO {
+            let Some(lot) = self.lots.front_mut() else {
+                break;
+            };
+            let take = quantity.min(lot.quantity);
+            cost_basis += take * lot.unit_cost;
+            lot.quantity -= take;
+            quantity -= take;
+            if lot.quantity <= Decimal::ZERO {
+                self.lots.pop_front();
+            }
+        }
+        Consumption {
+            quantity: requested,
+            cost_basis,
+            // Whatever is left unfilled could not be costed.
+            shortfall: quantity,
+        }
+    }
+}
+
+/// Running realized-gain figures accumulated per stock item over a run.
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct RealizedGain {
+    pub sale_value: Decimal,
+    pub cost_basis: Decimal,
+    pub realized_gain: Decimal,
+    pub shortfall_qty: Decimal,
+}
+
+/// A stock leg as projected out of `acTrns`: a stock item, the quantity moved,
+/// and the signed value (`credit - debit`, negative for a purchase/debit leg).
+/// Like `export::Transaction`, `amount`/`quantity` are still sourced from the
+/// aggregation's double-precision `$subtract`, so the `Decimal` here is exact
+/// only from this point forward, not back to the original ledger figures.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StockTransaction {
+    pub account: String,
+    #[serde(with = "rust_decimal::serde::float")]
+    pub quantity: Decimal,
+    #[serde(with = "rust_decimal::serde::float")]
+    pub amount: Decimal,
+}
+
+/// A consumed/added line emitted under a voucher's `INVENTORYENTRIES.LIST`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub struct InventoryEntry {
+    #[serde(rename = "STOCKITEMNAME")]
+    pub stock_item_name: String,
+    #[serde(rename = "ISDEEMEDPOSITIVE")]
+    pub is_deemed_positive: String,
+    #[serde(rename = "ACTUALQTY")]
+    pub actual_qty: String,
+    #[serde(rename = "BILLEDQTY")]
+    pub billed_qty: String,
+    #[serde(rename = "RATE")]
+    pub rate: String,
+    #[serde(serialize_with = "serialize_amount")]
+    pub amount: Decimal,
+}
+
+/// FIFO cost-basis engine shared across every voucher in a run.
+#[derive(Debug, Default)]
+pub struct StockLedger {
+    items: HashMap<String, ItemFifo>,
+    realized: HashMap<String, RealizedGain>,
+}
+
+impl StockLedger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Seed an opening-balance lot for an item so sales before the first
+    /// recorded purchase draw from the opening snapshot rather than underflow.
+    pub fn seed_opening(&mut self, item: String, quantity: Decimal, value: Decimal) {
+        if quantity <= Decimal::ZERO {
+            return;
+        }
+        let unit_cost = value / quantity;
+        self.items.entry(item).or_default().push(quantity, unit_cost);
+    }
+
+    /// Apply one stock leg, returning the inventory line to attach to the
+    /// voucher. A debit leg (`amount < 0`) pushes a lot; a credit leg
+    /// (`amount > 0`) consumes FIFO and books the realized gain.
+    pub fn record(&mut self, item: String, quantity: Decimal, amount: Decimal) -> InventoryEntry {
+        let value = amount.abs();
+        let fifo = self.items.entry(item.clone()).or_default();
+        if amount <= Decimal::ZERO {
+            // Purchase / debit leg.
+            let unit_cost = if quantity > Decimal::ZERO {
+                value / quantity
+            } else {
+                Decimal::ZERO
+            };
+            fifo.push(quantity, unit_cost);
+            InventoryEntry {
+                stock_item_name: item,
+                is_deemed_positive: "Yes".to_string(),
+                actual_qty: format!("{}", quantity),
+                billed_qty: format!("{}", quantity),
+                rate: format!("{:.2}", unit_cost.round_dp(2)),
+                amount,
+            }
+        } else {
+            // Sale / credit leg.
+            let consumption = fifo.consume(quantity);
+            let gain = self.realized.entry(item.clone()).or_default();
+            gain.sale_value += value;
+            gain.cost_basis += consumption.cost_basis;
+            gain.realized_gain += value - consumption.cost_basis;
+            gain.shortfall_qty += consumption.shortfall;
+            let rate = if quantity > Decimal::ZERO {
+                (value / quantity).round_dp(2)
+            } else {
+                Decimal::ZERO
+            };
+            InventoryEntry {
+                stock_item_name: item,
+                is_deemed_positive: "No".to_string(),
+                actual_qty: format!("-{}", quantity),
+                billed_qty: format!("-{}", quantity),
+                rate: format!("{:.2}", rate),
+                amount,
+            }
+        }
+    }
+
+    /// Per-item realized-gain figures accumulated so far.
+    pub fn realized(&self) -> &HashMap<String, RealizedGain> {
+        &self.realized
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn consume_draws_lots_fifo_and_reports_zero_shortfall_when_covered() {
+        let mut ledger = StockLedger::new();
+        // Two purchases: 10 units @ 10 each, then 5 units @ 15 each.
+        ledger.record("Widget".to_string(), Decimal::new(10, 0), Decimal::new(-100, 0));
+        ledger.record("Widget".to_string(), Decimal::new(5, 0), Decimal::new(-75, 0));
+
+        // Selling 12 draws all of the first lot (cost 100) plus 2 units from
+        // the second lot (cost 30) — oldest cost first, nothing short.
+        ledger.record("Widget".to_string(), Decimal::new(12, 0), Decimal::new(180, 0));
+
+        let gain = ledger.realized().get("Widget").unwrap();
+        assert_eq!(gain.cost_basis, Decimal::new(130, 0));
+        assert_eq!(gain.realized_gain, Decimal::new(50, 0));
+        assert_eq!(gain.shortfall_qty, Decimal::ZERO);
+    }
+
+    #[test]
+    fn consume_clamps_shortfall_once_lots_run_out() {
+        let mut ledger = StockLedger::new();
+        ledger.record("Widget".to_string(), Decimal::new(10, 0), Decimal::new(-100, 0));
+
+        // First sale of 12 only has 10 units of cost to draw on: 2 short.
+        let sale = ledger.record("Widget".to_string(), Decimal::new(12, 0), Decimal::new(120, 0));
+        assert_eq!(sale.amount, Decimal::new(120, 0));
+
+        let gain = ledger.realized().get("Widget").unwrap();
+        assert_eq!(gain.cost_basis, Decimal::new(100, 0));
+        assert_eq!(gain.shortfall_qty, Decimal::new(2, 0));
+
+        // A further sale with no lots left is short in full.
+        ledger.record("Widget".to_string(), Decimal::new(3, 0), Decimal::new(30, 0));
+        let gain = ledger.realized().get("Widget").unwrap();
+        assert_eq!(gain.shortfall_qty, Decimal::new(5, 0));
+    }
+}
@@ -0,0 +1,61 @@
+use serde::Deserialize;
+
+use crate::error::ExportError;
+use crate::export::ExportData;
+
+/// Tally's XML gateway listens here by default.
+pub const DEFAULT_TALLY_URL: &str = "http://localhost:9000";
+
+/// Typed view of Tally's import reply `RESPONSE` block.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub struct ImportResponse {
+    #[serde(default)]
+    pub created: i64,
+    #[serde(default)]
+    pub altered: i64,
+    #[serde(default)]
+    pub deleted: i64,
+    #[serde(rename = "LASTVCHID", default)]
+    pub last_vch_id: Option<i64>,
+    #[serde(default)]
+    pub exceptions: i64,
+    #[serde(default)]
+    pub errors: i64,
+    #[serde(rename = "LINEERROR", default)]
+    pub line_errors: Vec<String>,
+}
+
+impl ImportResponse {
+    /// Tally accepted the payload cleanly.
+    pub fn success(&self) -> bool {
+        self.errors == 0 && self.exceptions == 0
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+struct ResponseEnvelope {
+    response: ImportResponse,
+}
+
+/// Serialize the export envelope and POST it to Tally's XML gateway, returning
+/// the parsed import response instead of raw XML.
+pub async fn post_to_tally(url: &str, export: &ExportData) -> Result<ImportResponse, ExportError> {
+    let options = xml_serde::Options {
+        include_schema_location: false,
+    };
+    let body = xml_serde::to_string_custom(export, options).map_err(|e| ExportError::Xml(e.to_string()))?;
+    let client = reqwest::Client::new();
+    let text = client
+        .post(url)
+        .header("Content-Type", "text/xml")
+        .body(body)
+        .send()
+        .await?
+        .text()
+        .await?;
+    let envelope: ResponseEnvelope =
+        xml_serde::from_str(&text).map_err(|e| ExportError::Xml(e.to_string()))?;
+    Ok(envelope.response)
+}